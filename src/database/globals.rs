@@ -2,7 +2,7 @@ use crate::{database::Config, server_server::FedDest, utils, Error, Result};
 use ruma::{
     api::{
         client::sync::sync_events,
-        federation::discovery::{ServerSigningKeys, VerifyKey},
+        federation::discovery::{OldVerifyKey, ServerSigningKeys, VerifyKey},
     },
     DeviceId, EventId, MilliSecondsSinceUnixEpoch, RoomId, ServerName, ServerSigningKeyId, UserId,
 };
@@ -15,6 +15,8 @@ use std::{
     sync::{Arc, Mutex, RwLock},
     time::{Duration, Instant},
 };
+use futures_util::future::join_all;
+use rand::Rng;
 use tokio::sync::{broadcast, watch::Receiver, Mutex as TokioMutex, Semaphore};
 use tracing::error;
 use trust_dns_resolver::TokioAsyncResolver;
@@ -23,6 +25,45 @@ use super::abstraction::Tree;
 
 pub const COUNTER: &[u8] = b"c";
 
+/// Prefix under which superseded versions of our own signing keypair are
+/// stored, keyed by `oldkeypair_<version>`.
+const OLD_KEYPAIR_PREFIX: &[u8] = b"oldkeypair_";
+
+/// How long after being superseded a retired signing key stays valid: its
+/// `valid_until_ts` is set to the rotation time plus this period. Retired
+/// keys are kept only in `Globals`'s private key history; nothing in this
+/// module publishes them in `old_verify_keys`.
+const OLD_KEY_GRACE_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often the background rotation task wakes up to check whether the
+/// active signing key has become due for rotation.
+const KEY_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum age of the active signing key before it is rotated out.
+const KEY_ROTATION_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Base delay before the first retry of a transient federation failure.
+/// Each subsequent failure doubles it, up to [`MAX_FEDERATION_BACKOFF`].
+const FEDERATION_BACKOFF_BASE: Duration = Duration::from_millis(500);
+
+/// Upper bound on the computed backoff delay, regardless of how many times
+/// in a row a destination has failed.
+const MAX_FEDERATION_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// `base * 2^failures`, capped at `MAX_FEDERATION_BACKOFF` and jittered by
+/// up to 20% so that many destinations that failed at the same time don't
+/// all retry in lockstep.
+fn compute_backoff(failures: u32) -> Duration {
+    let backoff = FEDERATION_BACKOFF_BASE
+        .checked_mul(1u32 << failures.min(16)) // avoid overflowing the shift
+        .unwrap_or(MAX_FEDERATION_BACKOFF)
+        .min(MAX_FEDERATION_BACKOFF);
+
+    let jitter_ms = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64 / 5);
+
+    backoff + Duration::from_millis(jitter_ms)
+}
+
 type WellKnownMap = HashMap<Box<ServerName>, (FedDest, String)>;
 type TlsNameMap = HashMap<String, (Vec<IpAddr>, u16)>;
 type RateLimitState = (Instant, u32); // Time if last failed try, number of failed tries
@@ -31,12 +72,41 @@ type SyncHandle = (
     Receiver<Option<Result<sync_events::v3::Response>>>, // rx
 );
 
+/// Verify keys independent notaries agreed on, split the same way
+/// [`ServerSigningKeys`] itself is: `current` has no expiry, `old` expires
+/// at its `valid_until_ts`.
+#[derive(Default)]
+struct QuorumVerifyKeys {
+    current: BTreeMap<Box<ServerSigningKeyId>, VerifyKey>,
+    old: BTreeMap<Box<ServerSigningKeyId>, OldVerifyKey>,
+}
+
+impl QuorumVerifyKeys {
+    fn is_empty(&self) -> bool {
+        self.current.is_empty() && self.old.is_empty()
+    }
+}
+
+/// One version of our own Ed25519 signing keypair, plus the window in which
+/// it is valid. `valid_until_ts` is `None` for the currently active key.
+struct OwnSigningKey {
+    keypair: Arc<ruma::signatures::Ed25519KeyPair>,
+    valid_until_ts: Option<MilliSecondsSinceUnixEpoch>,
+    /// When this key was minted. Only meaningful for the active key; the
+    /// mint time of retired keys is not stored, so those loaded from the
+    /// database get the Unix epoch, which is harmless since they are never
+    /// rotated again.
+    minted_at: MilliSecondsSinceUnixEpoch,
+}
+
 pub struct Globals {
     pub actual_destination_cache: Arc<RwLock<WellKnownMap>>, // actual_destination, host
     pub tls_name_override: Arc<RwLock<TlsNameMap>>,
     pub(super) globals: Arc<dyn Tree>,
     pub config: Config,
-    keypair: Arc<ruma::signatures::Ed25519KeyPair>,
+    /// version -> keypair, the current active key is the one with a `None`
+    /// `valid_until_ts`.
+    keypairs: RwLock<BTreeMap<String, OwnSigningKey>>,
     dns_resolver: TokioAsyncResolver,
     jwt_decoding_key: Option<jsonwebtoken::DecodingKey<'static>>,
     federation_client: reqwest::Client,
@@ -45,6 +115,10 @@ pub struct Globals {
     pub bad_event_ratelimiter: Arc<RwLock<HashMap<Box<EventId>, RateLimitState>>>,
     pub bad_signature_ratelimiter: Arc<RwLock<HashMap<Vec<String>, RateLimitState>>>,
     pub servername_ratelimiter: Arc<RwLock<HashMap<Box<ServerName>, Arc<Semaphore>>>>,
+    /// Tracks transient federation request failures per destination so that
+    /// [`Globals::request_with_backoff`] can back off exponentially instead
+    /// of hammering a server that is temporarily unreachable.
+    pub federation_backoff: Arc<RwLock<HashMap<Box<ServerName>, RateLimitState>>>,
     pub sync_receivers: RwLock<HashMap<(Box<UserId>, Box<DeviceId>), SyncHandle>>,
     pub roomid_mutex_insert: RwLock<HashMap<Box<RoomId>, Arc<Mutex<()>>>>,
     pub roomid_mutex_state: RwLock<HashMap<Box<RoomId>, Arc<TokioMutex<()>>>>,
@@ -87,7 +161,7 @@ impl Globals {
         globals: Arc<dyn Tree>,
         server_signingkeys: Arc<dyn Tree>,
         config: Config,
-    ) -> Result<Self> {
+    ) -> Result<Arc<Self>> {
         let keypair_bytes = globals.get(b"keypair")?.map_or_else(
             || {
                 let keypair = utils::generate_keypair();
@@ -97,29 +171,8 @@ impl Globals {
             |s| Ok(s.to_vec()),
         )?;
 
-        let mut parts = keypair_bytes.splitn(2, |&b| b == 0xff);
-
-        let keypair = utils::string_from_bytes(
-            // 1. version
-            parts
-                .next()
-                .expect("splitn always returns at least one element"),
-        )
-        .map_err(|_| Error::bad_database("Invalid version bytes in keypair."))
-        .and_then(|version| {
-            // 2. key
-            parts
-                .next()
-                .ok_or_else(|| Error::bad_database("Invalid keypair format in database."))
-                .map(|key| (version, key))
-        })
-        .and_then(|(version, key)| {
-            ruma::signatures::Ed25519KeyPair::from_der(key, version)
-                .map_err(|_| Error::bad_database("Private or public keys are invalid."))
-        });
-
-        let keypair = match keypair {
-            Ok(k) => k,
+        let (active_version, active_keypair) = match parse_keypair_bytes(&keypair_bytes) {
+            Ok(parsed) => parsed,
             Err(e) => {
                 error!("Keypair invalid. Deleting...");
                 globals.remove(b"keypair")?;
@@ -127,6 +180,48 @@ impl Globals {
             }
         };
 
+        let mut keypairs = BTreeMap::new();
+
+        for (key, value) in globals.scan_prefix(OLD_KEYPAIR_PREFIX.to_vec()) {
+            let version = utils::string_from_bytes(&key[OLD_KEYPAIR_PREFIX.len()..])
+                .map_err(|_| Error::bad_database("Invalid old keypair key in database."))?;
+
+            let (valid_until_ts, der) = decode_old_keypair_entry(&value)?;
+
+            let keypair = ruma::signatures::Ed25519KeyPair::from_der(der, version.clone())
+                .map_err(|_| Error::bad_database("Old private or public keys are invalid."))?;
+
+            keypairs.insert(
+                version,
+                OwnSigningKey {
+                    keypair: Arc::new(keypair),
+                    valid_until_ts: Some(MilliSecondsSinceUnixEpoch(ruma::UInt::new(valid_until_ts).ok_or_else(|| {
+                        Error::bad_database("Invalid valid_until_ts in database.")
+                    })?)),
+                    minted_at: MilliSecondsSinceUnixEpoch(ruma::UInt::new(0).expect("0 fits in UInt")),
+                },
+            );
+        }
+
+        let minted_at = globals
+            .get(b"keypair_minted_at")?
+            .and_then(|bytes| bytes.as_slice().try_into().ok())
+            .map(u64::from_be_bytes)
+            .unwrap_or_else(utils::millis_since_unix_epoch);
+        globals.insert(b"keypair_minted_at", &minted_at.to_be_bytes())?;
+
+        keypairs.insert(
+            active_version,
+            OwnSigningKey {
+                keypair: Arc::new(active_keypair),
+                valid_until_ts: None,
+                minted_at: MilliSecondsSinceUnixEpoch(
+                    ruma::UInt::new(minted_at)
+                        .ok_or_else(|| Error::bad_database("Invalid keypair_minted_at in database."))?,
+                ),
+            },
+        );
+
         let tls_name_override = Arc::new(RwLock::new(TlsNameMap::new()));
 
         let jwt_decoding_key = config
@@ -148,7 +243,7 @@ impl Globals {
         let s = Self {
             globals,
             config,
-            keypair: Arc::new(keypair),
+            keypairs: RwLock::new(keypairs),
             dns_resolver: TokioAsyncResolver::tokio_from_system_conf().map_err(|e| {
                 error!(
                     "Failed to set up trust dns resolver with system config: {}",
@@ -165,6 +260,7 @@ impl Globals {
             bad_event_ratelimiter: Arc::new(RwLock::new(HashMap::new())),
             bad_signature_ratelimiter: Arc::new(RwLock::new(HashMap::new())),
             servername_ratelimiter: Arc::new(RwLock::new(HashMap::new())),
+            federation_backoff: Arc::new(RwLock::new(HashMap::new())),
             roomid_mutex_state: RwLock::new(HashMap::new()),
             roomid_mutex_insert: RwLock::new(HashMap::new()),
             roomid_mutex_federation: RwLock::new(HashMap::new()),
@@ -174,12 +270,115 @@ impl Globals {
 
         fs::create_dir_all(s.get_media_folder())?;
 
+        let s = Arc::new(s);
+        s.spawn_signing_key_rotation_task();
+
         Ok(s)
     }
 
-    /// Returns this server's keypair.
-    pub fn keypair(&self) -> &ruma::signatures::Ed25519KeyPair {
-        &self.keypair
+    /// Returns this server's currently active signing keypair.
+    ///
+    /// Cheap to call: the keypair is reference-counted, so this just bumps
+    /// the refcount rather than copying key material.
+    pub fn keypair(&self) -> Arc<ruma::signatures::Ed25519KeyPair> {
+        let keypairs = self.keypairs.read().unwrap();
+        keypairs
+            .values()
+            .find(|k| k.valid_until_ts.is_none())
+            .map(|k| Arc::clone(&k.keypair))
+            .expect("there is always exactly one active signing key")
+    }
+
+    /// Mints a fresh Ed25519 keypair, promotes it to the active signing key,
+    /// and demotes the previous one into the retired-key pool with
+    /// `valid_until_ts` set to now plus [`OLD_KEY_GRACE_PERIOD`].
+    pub fn rotate_signing_key(&self) -> Result<()> {
+        let mut keypairs = self.keypairs.write().unwrap();
+
+        let current_version = keypairs
+            .iter()
+            .find(|(_, k)| k.valid_until_ts.is_none())
+            .map(|(version, _)| version.clone())
+            .expect("there is always exactly one active signing key");
+
+        let current_bytes = self
+            .globals
+            .get(b"keypair")?
+            .ok_or_else(|| Error::bad_database("Active keypair missing from database."))?;
+
+        let new_bytes = utils::generate_keypair();
+        let (new_version, new_keypair) = parse_keypair_bytes(&new_bytes)?;
+
+        let now_ms = utils::millis_since_unix_epoch();
+        let valid_until_ms = now_ms + OLD_KEY_GRACE_PERIOD.as_millis() as u64;
+        let valid_until_ts = MilliSecondsSinceUnixEpoch(
+            ruma::UInt::new(valid_until_ms)
+                .ok_or_else(|| Error::bad_database("valid_until_ts overflowed UInt."))?,
+        );
+
+        // `current_bytes` is `version ++ 0xff ++ der`; the version is
+        // already known from the map key (`current_version`), so only the
+        // `der` half needs to survive into the retired-key entry.
+        let (_, current_der) = split_version_and_der(&current_bytes)?;
+        let old_entry = encode_old_keypair_entry(valid_until_ms, current_der);
+
+        self.globals.insert(
+            &[OLD_KEYPAIR_PREFIX, current_version.as_bytes()].concat(),
+            &old_entry,
+        )?;
+        self.globals.insert(b"keypair", &new_bytes)?;
+        self.globals
+            .insert(b"keypair_minted_at", &now_ms.to_be_bytes())?;
+
+        if let Some(entry) = keypairs.get_mut(&current_version) {
+            entry.valid_until_ts = Some(valid_until_ts);
+        }
+
+        keypairs.insert(
+            new_version,
+            OwnSigningKey {
+                keypair: Arc::new(new_keypair),
+                valid_until_ts: None,
+                minted_at: MilliSecondsSinceUnixEpoch(
+                    ruma::UInt::new(now_ms).ok_or_else(|| Error::bad_database("now overflowed UInt."))?,
+                ),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Wakes up every [`KEY_ROTATION_CHECK_INTERVAL`] and rotates the active
+    /// signing key once it is older than [`KEY_ROTATION_MAX_AGE`]. Spawned
+    /// once by [`Globals::load`].
+    pub fn spawn_signing_key_rotation_task(self: &Arc<Self>) {
+        let globals = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(KEY_ROTATION_CHECK_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let due_for_rotation = globals
+                    .keypairs
+                    .read()
+                    .unwrap()
+                    .values()
+                    .find(|k| k.valid_until_ts.is_none())
+                    .map(|k| {
+                        utils::millis_since_unix_epoch().saturating_sub(u64::from(k.minted_at.get()))
+                            >= KEY_ROTATION_MAX_AGE.as_millis() as u64
+                    })
+                    .unwrap_or(false);
+
+                if due_for_rotation {
+                    if let Err(e) = globals.rotate_signing_key() {
+                        error!("Failed to rotate signing key: {}", e);
+                    }
+                }
+            }
+        });
     }
 
     /// Returns a reqwest client which can be used to send requests
@@ -264,7 +463,6 @@ impl Globals {
         &self.config.turn_secret
     }
 
-    /// TODO: the key valid until timestamp is only honored in room version > 4
     /// Remove the outdated keys and insert the new ones.
     ///
     /// This doesn't actually check that the keys provided are newer than the old set.
@@ -330,6 +528,255 @@ impl Globals {
         Ok(signingkeys)
     }
 
+    /// Like [`Globals::signing_keys_for`], but drops `old_verify_keys`
+    /// whose `valid_until_ts` is before `origin_server_ts` — correct
+    /// regardless of room version, unlike checking `valid_until_ts` only
+    /// for room version > 4.
+    pub fn signing_keys_for_timestamp(
+        &self,
+        origin: &ServerName,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+    ) -> Result<BTreeMap<Box<ServerSigningKeyId>, VerifyKey>> {
+        let signingkeys = self
+            .server_signingkeys
+            .get(origin.as_bytes())?
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .map(|keys: ServerSigningKeys| {
+                let mut tree = keys.verify_keys;
+                tree.extend(
+                    keys.old_verify_keys
+                        .into_iter()
+                        .filter(|(_, old)| old.valid_until_ts >= origin_server_ts)
+                        .map(|(id, old)| (id, VerifyKey::new(old.key))),
+                );
+                tree
+            })
+            .unwrap_or_else(BTreeMap::new);
+
+        Ok(signingkeys)
+    }
+
+    /// Fetches verify keys for `origin`, valid at `origin_server_ts`, from
+    /// every entry in `trusted_servers()` plus `origin` itself, concurrently.
+    ///
+    /// `origin`'s own answer is never counted as a vote, and an
+    /// `old_verify_keys` candidate whose `valid_until_ts` doesn't cover
+    /// `origin_server_ts` is dropped before voting even starts — a retired
+    /// key cannot be revived. A key id is accepted only once at least
+    /// `min_independent_agreement` independent notaries report the exact
+    /// same bytes (and, for old keys, the same `valid_until_ts`) for it;
+    /// key ids on which independent notaries disagree are never accepted,
+    /// and the batch of notaries queried has its failure count in
+    /// `bad_signature_ratelimiter` incremented, same as any other bad
+    /// signature.
+    ///
+    /// Meant to be called when [`Globals::signing_keys_for_timestamp`] has
+    /// nothing valid for the timestamp in question; see
+    /// [`Globals::verified_signing_keys_for`].
+    async fn fetch_signing_keys_quorum(
+        &self,
+        origin: &ServerName,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+        min_independent_agreement: usize,
+    ) -> Result<QuorumVerifyKeys> {
+        let mut targets: Vec<Box<ServerName>> = self.trusted_servers().to_vec();
+        if !targets.iter().any(|notary| notary.as_ref() == origin) {
+            targets.push(origin.to_owned());
+        }
+
+        let responses = join_all(
+            targets
+                .iter()
+                .map(|notary| self.query_server_signing_keys(notary, origin)),
+        )
+        .await;
+
+        let mut independent_respondents = 0usize;
+        let mut current_votes = Vec::new();
+        let mut old_votes = Vec::new();
+
+        for (notary, response) in targets.iter().zip(responses) {
+            let Some(response) = response else { continue };
+            let is_independent = notary.as_ref() != origin;
+            if is_independent {
+                independent_respondents += 1;
+            }
+
+            for (key_id, key) in response.verify_keys {
+                current_votes.push((is_independent, key_id, key.key));
+            }
+            for (key_id, old) in response.old_verify_keys {
+                if old.valid_until_ts >= origin_server_ts {
+                    old_votes.push((is_independent, key_id, (old.key, old.valid_until_ts)));
+                }
+            }
+        }
+
+        let current_tally = tally_key_votes(current_votes, min_independent_agreement);
+        let old_tally = tally_key_votes(old_votes, min_independent_agreement);
+
+        if !current_tally.conflicted.is_empty() || !old_tally.conflicted.is_empty() {
+            let notary_names: Vec<String> =
+                targets.iter().map(|s| s.as_str().to_owned()).collect();
+            let mut bad_signature_ratelimiter = self.bad_signature_ratelimiter.write().unwrap();
+            let failures = bad_signature_ratelimiter
+                .get(&notary_names)
+                .map_or(0, |(_, failures)| *failures);
+            bad_signature_ratelimiter.insert(notary_names, (Instant::now(), failures + 1));
+        }
+
+        if independent_respondents < min_independent_agreement {
+            return Ok(QuorumVerifyKeys::default());
+        }
+
+        Ok(QuorumVerifyKeys {
+            current: current_tally
+                .agreed
+                .into_iter()
+                .map(|(key_id, key)| (key_id, VerifyKey::new(key)))
+                .collect(),
+            old: old_tally
+                .agreed
+                .into_iter()
+                .map(|(key_id, (key, valid_until_ts))| {
+                    (key_id, OldVerifyKey::new(valid_until_ts, key))
+                })
+                .collect(),
+        })
+    }
+
+    /// Like [`Globals::signing_keys_for_timestamp`], but falls back to
+    /// [`Globals::fetch_signing_keys_quorum`] when the cache has nothing
+    /// valid for `origin_server_ts`, persisting whatever quorum agrees on
+    /// via [`Globals::add_signing_key`] (preserving old keys' validity
+    /// windows) before re-reading the now-populated cache.
+    pub async fn verified_signing_keys_for(
+        &self,
+        origin: &ServerName,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+        min_independent_agreement: usize,
+    ) -> Result<BTreeMap<Box<ServerSigningKeyId>, VerifyKey>> {
+        let cached = self.signing_keys_for_timestamp(origin, origin_server_ts)?;
+        if !cached.is_empty() {
+            return Ok(cached);
+        }
+
+        let quorum = self
+            .fetch_signing_keys_quorum(origin, origin_server_ts, min_independent_agreement)
+            .await?;
+        if quorum.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        self.add_signing_key(
+            origin,
+            ServerSigningKeys {
+                verify_keys: quorum.current,
+                old_verify_keys: quorum.old,
+                ..ServerSigningKeys::new(origin.to_owned(), MilliSecondsSinceUnixEpoch::now())
+            },
+        )?;
+
+        self.signing_keys_for_timestamp(origin, origin_server_ts)
+    }
+
+    /// Queries a single notary (or `origin` itself) for `origin`'s signing
+    /// keys, backed off like any other federation request. Returns `None`
+    /// on any failure, so the caller can just treat it as a non-responder.
+    async fn query_server_signing_keys(
+        &self,
+        notary: &ServerName,
+        origin: &ServerName,
+    ) -> Option<ServerSigningKeys> {
+        self.request_with_backoff(notary, || self.query_server_signing_keys_once(notary, origin))
+            .await
+            .ok()
+    }
+
+    async fn query_server_signing_keys_once(
+        &self,
+        notary: &ServerName,
+        origin: &ServerName,
+    ) -> Result<ServerSigningKeys> {
+        let response = if notary == origin {
+            self.federation_client()
+                .get(format!("https://{notary}/_matrix/key/v2/server"))
+                .send()
+                .await?
+                .json::<ServerSigningKeys>()
+                .await?
+        } else {
+            #[derive(serde::Deserialize)]
+            struct QueryResponse {
+                server_keys: Vec<ServerSigningKeys>,
+            }
+
+            self.federation_client()
+                .get(format!("https://{notary}/_matrix/key/v2/query/{origin}"))
+                .send()
+                .await?
+                .json::<QueryResponse>()
+                .await?
+                .server_keys
+                .into_iter()
+                .find(|keys| keys.server_name == origin)
+                .ok_or_else(|| Error::bad_server_response("Notary has no keys for that server."))?
+        };
+
+        if response.server_name == origin {
+            Ok(response)
+        } else {
+            Err(Error::bad_server_response(
+                "Notary returned keys for the wrong server.",
+            ))
+        }
+    }
+
+    /// Runs `request` against `server`, waiting out any remaining backoff
+    /// first. A successful response clears the failure count; a non-fatal
+    /// error (`Error::is_non_fatal`) increments it, doubling the next wait;
+    /// any other error leaves it unchanged. Doesn't loop
+    /// and retry by itself -- wraps one attempt, like `servername_ratelimiter`
+    /// wraps concurrent access to a destination.
+    pub async fn request_with_backoff<F, Fut, T>(&self, server: &ServerName, request: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let wait = self
+            .federation_backoff
+            .read()
+            .unwrap()
+            .get(server)
+            .map(|&(last_failure, failures)| {
+                // `failures` counts completed failures (>= 1 once any entry
+                // exists), so the Nth retry waits compute_backoff(N - 1):
+                // the first retry waits FEDERATION_BACKOFF_BASE, as promised
+                // above, not double it.
+                compute_backoff(failures.saturating_sub(1)).saturating_sub(last_failure.elapsed())
+            });
+
+        if let Some(wait) = wait {
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+
+        match request().await {
+            Ok(value) => {
+                self.federation_backoff.write().unwrap().remove(server);
+                Ok(value)
+            }
+            Err(e) if e.is_non_fatal() => {
+                let mut backoff = self.federation_backoff.write().unwrap();
+                let failures = backoff.get(server).map_or(0, |&(_, failures)| failures) + 1;
+                backoff.insert(server.to_owned(), (Instant::now(), failures));
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn database_version(&self) -> Result<u64> {
         self.globals.get(b"version")?.map_or(Ok(0), |version| {
             utils::u64_from_bytes(&version)
@@ -359,6 +806,190 @@ impl Globals {
     }
 }
 
+/// Splits the `<version>\xff<der>` wire format used to persist our own
+/// keypairs into its two halves.
+fn split_version_and_der(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    let mut parts = bytes.splitn(2, |&b| b == 0xff);
+
+    let version = parts
+        .next()
+        .expect("splitn always returns at least one element");
+    let der = parts
+        .next()
+        .ok_or_else(|| Error::bad_database("Invalid keypair format in database."))?;
+
+    Ok((version, der))
+}
+
+/// Parses the `<version>\xff<der>` wire format used to persist our active
+/// keypair under the `keypair` key. Retired keys under
+/// `oldkeypair_<version>` use a different format; see
+/// [`decode_old_keypair_entry`].
+fn parse_keypair_bytes(bytes: &[u8]) -> Result<(String, ruma::signatures::Ed25519KeyPair)> {
+    let (version, der) = split_version_and_der(bytes)?;
+
+    let version = utils::string_from_bytes(version)
+        .map_err(|_| Error::bad_database("Invalid version bytes in keypair."))?;
+
+    ruma::signatures::Ed25519KeyPair::from_der(der, version.clone())
+        .map(|keypair| (version, keypair))
+        .map_err(|_| Error::bad_database("Private or public keys are invalid."))
+}
+
+/// Serializes a retired signing key's record: `valid_until_ts` (8 bytes, big
+/// endian) `++ 0xff ++ der`. `der` alone, without the version prefix that
+/// `keypair`'s own wire format carries — the version is already recoverable
+/// from the `oldkeypair_<version>` key name.
+fn encode_old_keypair_entry(valid_until_ms: u64, der: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(8 + 1 + der.len());
+    entry.extend_from_slice(&valid_until_ms.to_be_bytes());
+    entry.push(0xff);
+    entry.extend_from_slice(der);
+    entry
+}
+
+/// Inverse of [`encode_old_keypair_entry`].
+fn decode_old_keypair_entry(bytes: &[u8]) -> Result<(u64, &[u8])> {
+    // The timestamp is fixed-width and may itself contain 0xff bytes, so
+    // split by position rather than at the first 0xff.
+    if bytes.len() < 9 || bytes[8] != 0xff {
+        return Err(Error::bad_database("Invalid old keypair entry in database."));
+    }
+
+    let valid_until_ts =
+        u64::from_be_bytes(bytes[..8].try_into().expect("slice is exactly 8 bytes"));
+    let der = &bytes[9..];
+
+    Ok((valid_until_ts, der))
+}
+
+/// Result of [`tally_key_votes`]: key ids with enough independent agreement
+/// to trust, and key ids independent notaries disagreed on.
+struct KeyVoteTally<K, V> {
+    agreed: HashMap<K, V>,
+    conflicted: Vec<K>,
+}
+
+/// Tallies `(is_independent, key_id, candidate)` votes gathered by
+/// [`Globals::fetch_signing_keys_quorum`]. Only independent votes count;
+/// a key id is accepted once `min_independent_agreement` of them agree on
+/// the same candidate, and flagged as conflicted (never accepted) if they
+/// don't.
+fn tally_key_votes<K: std::hash::Hash + Eq, V: std::hash::Hash + Eq>(
+    votes: impl IntoIterator<Item = (bool, K, V)>,
+    min_independent_agreement: usize,
+) -> KeyVoteTally<K, V> {
+    let mut tallies: HashMap<K, HashMap<V, usize>> = HashMap::new();
+
+    for (is_independent, key_id, key) in votes {
+        if is_independent {
+            *tallies.entry(key_id).or_default().entry(key).or_insert(0) += 1;
+        }
+    }
+
+    let mut agreed = HashMap::new();
+    let mut conflicted = Vec::new();
+
+    for (key_id, candidates) in tallies {
+        if candidates.len() > 1 {
+            conflicted.push(key_id);
+            continue;
+        }
+
+        if let Some((key, count)) = candidates.into_iter().next() {
+            if count >= min_independent_agreement {
+                agreed.insert(key_id, key);
+            }
+        }
+    }
+
+    KeyVoteTally { agreed, conflicted }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compute_backoff, decode_old_keypair_entry, encode_old_keypair_entry, tally_key_votes,
+        FEDERATION_BACKOFF_BASE, MAX_FEDERATION_BACKOFF,
+    };
+
+    #[test]
+    fn old_keypair_entry_round_trip() {
+        // DER bytes are arbitrary binary data and may themselves contain
+        // 0xff bytes; the encoding must not get confused by that.
+        let der = [0x01, 0xff, 0x02, 0x03, 0xff, 0x00];
+        let valid_until_ms = 1_700_000_000_000_u64;
+
+        let entry = encode_old_keypair_entry(valid_until_ms, &der);
+        let (decoded_ts, decoded_der) = decode_old_keypair_entry(&entry).unwrap();
+
+        assert_eq!(decoded_ts, valid_until_ms);
+        assert_eq!(decoded_der, der);
+    }
+
+    #[test]
+    fn tally_key_votes_ignores_origins_own_vote() {
+        let votes = vec![(false, "ed25519:1", "origin_key".to_owned())];
+
+        let tally = tally_key_votes(votes, 1);
+
+        assert!(tally.agreed.is_empty());
+        assert!(tally.conflicted.is_empty());
+    }
+
+    #[test]
+    fn tally_key_votes_requires_minimum_independent_agreement() {
+        let votes = vec![(true, "ed25519:1", "key_a".to_owned())];
+
+        assert!(tally_key_votes(votes.clone(), 1).agreed.contains_key("ed25519:1"));
+        assert!(!tally_key_votes(votes, 2).agreed.contains_key("ed25519:1"));
+    }
+
+    #[test]
+    fn tally_key_votes_flags_disagreement_as_conflicted() {
+        let votes = vec![
+            (true, "ed25519:1", "key_a".to_owned()),
+            (true, "ed25519:1", "key_b".to_owned()),
+        ];
+
+        let tally = tally_key_votes(votes, 1);
+
+        assert!(tally.agreed.is_empty());
+        assert_eq!(tally.conflicted, vec!["ed25519:1"]);
+    }
+
+    #[test]
+    fn tally_key_votes_treats_differing_validity_windows_as_disagreement() {
+        // Same key id and bytes, but notaries disagree on the expiry --
+        // an old key whose window shrank or grew shouldn't silently win by
+        // majority, so this must be treated as a conflict, not an agreement.
+        let votes = vec![
+            (true, "ed25519:1", ("key_a".to_owned(), 100_u64)),
+            (true, "ed25519:1", ("key_a".to_owned(), 200_u64)),
+        ];
+
+        let tally = tally_key_votes(votes, 1);
+
+        assert!(tally.agreed.is_empty());
+        assert_eq!(tally.conflicted, vec!["ed25519:1"]);
+    }
+
+    #[test]
+    fn compute_backoff_doubles_and_caps() {
+        // Jitter adds up to 20%, so compare against the un-jittered range.
+        assert!(compute_backoff(0) >= FEDERATION_BACKOFF_BASE);
+        assert!(compute_backoff(0) < FEDERATION_BACKOFF_BASE * 2);
+
+        assert!(compute_backoff(1) >= FEDERATION_BACKOFF_BASE * 2);
+        assert!(compute_backoff(1) < FEDERATION_BACKOFF_BASE * 3);
+
+        // Enough failures that 2^failures would overflow the shift; must
+        // saturate at the cap instead of panicking.
+        assert!(compute_backoff(64) <= MAX_FEDERATION_BACKOFF * 6 / 5);
+        assert!(compute_backoff(u32::MAX) <= MAX_FEDERATION_BACKOFF * 6 / 5);
+    }
+}
+
 fn reqwest_client_builder(config: &Config) -> Result<reqwest::ClientBuilder> {
     let mut reqwest_client_builder = reqwest::Client::builder()
         .connect_timeout(Duration::from_secs(30))
@@ -0,0 +1,53 @@
+use thiserror::Error as ThisError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("{0}")]
+    BadConfig(&'static str),
+
+    #[error("{0}")]
+    BadDatabase(&'static str),
+
+    #[error("{0}")]
+    BadServerResponse(&'static str),
+
+    #[error("{0}")]
+    Reqwest(#[from] reqwest::Error),
+}
+
+impl Error {
+    pub fn bad_config(message: &'static str) -> Self {
+        Self::BadConfig(message)
+    }
+
+    pub fn bad_database(message: &'static str) -> Self {
+        Self::BadDatabase(message)
+    }
+
+    pub fn bad_server_response(message: &'static str) -> Self {
+        Self::BadServerResponse(message)
+    }
+
+    /// Whether this failure is transient -- a transport-level problem
+    /// (timeout, connection refused, DNS failure) that might well succeed
+    /// if retried later -- as opposed to a response we understood and
+    /// rejected, which retrying won't change.
+    pub fn is_non_fatal(&self) -> bool {
+        match self {
+            Self::Reqwest(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            Self::BadConfig(_) | Self::BadDatabase(_) | Self::BadServerResponse(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+
+    #[test]
+    fn bad_database_is_fatal() {
+        assert!(!Error::bad_database("test").is_non_fatal());
+    }
+}